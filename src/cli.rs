@@ -0,0 +1,58 @@
+//! Command-line interface: a `run` subcommand for the strategy loop, plus
+//! subcommands that expose the existing `order_handler` functions for
+//! ad-hoc manual intervention.
+
+#[derive(clap::Parser, Debug)]
+#[command(author, version, about)]
+pub(crate) struct Cli {
+    #[command(subcommand)]
+    pub(crate) command: Command,
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub(crate) enum Command {
+    /// Run the delta/Bollinger strategy loop
+    Run {
+        /// Path to the settings JSON file
+        #[arg(long, default_value = "settings.json")]
+        config: String,
+    },
+    /// Print open positions
+    Position,
+    /// Market-close any open position on a market
+    Close {
+        market: String,
+    },
+    /// Cancel all trigger orders on a market
+    Cancel {
+        market: String,
+    },
+    /// Place a manual market order
+    Order {
+        market: String,
+        side: CliSide,
+        size: rust_decimal::Decimal,
+    },
+}
+
+/// Mirrors `ftx::rest::Side` so it can be parsed as a CLI argument
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub(crate) enum CliSide {
+    Buy,
+    Sell,
+}
+
+impl From<CliSide> for ftx::rest::Side {
+    fn from(side: CliSide) -> ftx::rest::Side {
+        match side {
+            CliSide::Buy => ftx::rest::Side::Buy,
+            CliSide::Sell => ftx::rest::Side::Sell,
+        }
+    }
+}
+
+/// Set up the live FTX REST connection shared by every subcommand
+pub(crate) fn connect_live_api() -> ftx::rest::Rest {
+    dotenv::dotenv().ok();
+    ftx::rest::Rest::new(ftx::options::Options::from_env())
+}
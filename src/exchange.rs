@@ -0,0 +1,323 @@
+//! Exchange abstraction used to run the delta/Bollinger strategy against
+//! either the live FTX endpoint or a `SimulatedExchange` replaying historical
+//! orderbook snapshots, so the strategy can be backtested without risking
+//! capital.
+
+use std::collections::VecDeque;
+
+/// Maximum number of resting limit or trigger orders the simulated exchange
+/// will hold before rejecting new ones.
+const MAX_RESTING_ORDERS: usize = 50;
+
+/// The request surface the strategy loop needs from an exchange, whether
+/// that's the live FTX REST API or a local replay.
+#[async_trait::async_trait]
+pub(crate) trait Exchange {
+    async fn get_orderbook(&mut self, market_name: &str, depth: u32) -> Option<(rust_decimal::Decimal, rust_decimal::Decimal)>;
+    async fn get_future_price(&mut self, market_name: &str) -> Option<(rust_decimal::Decimal, rust_decimal::Decimal)>;
+    async fn get_open_position(&mut self, market_name: &str) -> bool;
+    async fn place_market_order(&mut self, market_name: &str, side: ftx::rest::Side, size: rust_decimal::Decimal) -> bool;
+    async fn place_limit_order(&mut self, market_name: &str, side: ftx::rest::Side, size: rust_decimal::Decimal, price: rust_decimal::Decimal) -> bool;
+    async fn place_trigger_orders(&mut self, market_name: &str, side: ftx::rest::Side, size: rust_decimal::Decimal, tp_price: rust_decimal::Decimal, sl_price: rust_decimal::Decimal) -> bool;
+    async fn market_close_order(&mut self, market_name: &str) -> bool;
+    async fn cancel_all_trigger_orders(&mut self, market_name: &str) -> bool;
+}
+
+/// Wraps the live FTX REST connection, delegating to the existing
+/// `order_handler` functions.
+pub(crate) struct LiveExchange {
+    pub(crate) api: ftx::rest::Rest,
+}
+
+#[async_trait::async_trait]
+impl Exchange for LiveExchange {
+    async fn get_orderbook(&mut self, market_name: &str, depth: u32) -> Option<(rust_decimal::Decimal, rust_decimal::Decimal)> {
+        let order_book = self.api.request(ftx::rest::GetOrderBook {
+            market_name: String::from(market_name),
+            depth: Option::from(depth),
+        }).await.ok()?;
+        Some((order_book.bids[0].1, order_book.asks[0].1))
+    }
+
+    async fn get_future_price(&mut self, market_name: &str) -> Option<(rust_decimal::Decimal, rust_decimal::Decimal)> {
+        let future_result = self.api.request(ftx::rest::GetFuture {
+            future_name: String::from(market_name),
+        }).await.ok()?;
+        Some((future_result.bid?, future_result.ask?))
+    }
+
+    async fn get_open_position(&mut self, market_name: &str) -> bool {
+        crate::order_handler::get_open_position(&self.api, market_name).await
+    }
+
+    async fn place_market_order(&mut self, market_name: &str, side: ftx::rest::Side, size: rust_decimal::Decimal) -> bool {
+        crate::order_handler::place_market_order(&self.api, crate::order_handler::NewMarketOrder {
+            market: String::from(market_name),
+            side,
+            size,
+        }).await.is_some()
+    }
+
+    async fn place_limit_order(&mut self, market_name: &str, side: ftx::rest::Side, size: rust_decimal::Decimal, price: rust_decimal::Decimal) -> bool {
+        crate::order_handler::place_limit_order(&self.api, crate::order_handler::NewLimitOrder {
+            market: String::from(market_name),
+            side,
+            size,
+            price,
+            post_only: true,
+        }).await.is_some()
+    }
+
+    async fn place_trigger_orders(&mut self, market_name: &str, side: ftx::rest::Side, size: rust_decimal::Decimal, tp_price: rust_decimal::Decimal, sl_price: rust_decimal::Decimal) -> bool {
+        crate::order_handler::place_trigger_orders(&self.api, market_name, side, size, tp_price, sl_price, None).await
+    }
+
+    async fn market_close_order(&mut self, market_name: &str) -> bool {
+        crate::order_handler::market_close_order(&self.api, market_name).await
+    }
+
+    async fn cancel_all_trigger_orders(&mut self, market_name: &str) -> bool {
+        crate::order_handler::cancel_all_trigger_orders(&self.api, market_name).await
+    }
+}
+
+/// One replayed orderbook snapshot, e.g. a row parsed from a historical
+/// CSV/Parquet dump.
+#[derive(Debug, Clone)]
+pub(crate) struct OrderBookSnapshot {
+    pub(crate) bid: rust_decimal::Decimal,
+    pub(crate) ask: rust_decimal::Decimal,
+    pub(crate) bid_volume: rust_decimal::Decimal,
+    pub(crate) ask_volume: rust_decimal::Decimal,
+}
+
+/// Cash balance, position and realized PnL held by the simulated account.
+#[derive(Debug, Clone)]
+pub(crate) struct Account {
+    pub(crate) cash: rust_decimal::Decimal,
+    pub(crate) position_size: rust_decimal::Decimal,
+    /// Weighted-average entry price of the current `position_size`, used to
+    /// split a closing fill's proceeds into realized PnL vs. a still-open
+    /// mark
+    pub(crate) avg_entry_price: rust_decimal::Decimal,
+    pub(crate) realized_pnl: rust_decimal::Decimal,
+}
+
+impl Default for Account {
+    fn default() -> Account {
+        Account {
+            cash: rust_decimal::Decimal::from(0),
+            position_size: rust_decimal::Decimal::from(0),
+            avg_entry_price: rust_decimal::Decimal::from(0),
+            realized_pnl: rust_decimal::Decimal::from(0),
+        }
+    }
+}
+
+/// Which leg of a take-profit/stop-loss bracket a resting trigger order
+/// represents. The two legs fill in opposite directions relative to the
+/// trigger price, so the crossing rule can't be derived from `side` alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TriggerKind {
+    TakeProfit,
+    Stop,
+}
+
+/// A resting limit or trigger order held by the simulated exchange.
+/// `kind` is `None` for plain resting limit orders and `Some` for the
+/// take-profit/stop-loss legs placed by `place_trigger_orders`.
+#[derive(Debug, Clone)]
+struct RestingOrder {
+    side: ftx::rest::Side,
+    size: rust_decimal::Decimal,
+    trigger_price: rust_decimal::Decimal,
+    kind: Option<TriggerKind>,
+}
+
+/// Drives the strategy against a replay of historical orderbook snapshots
+/// instead of the live FTX endpoint.
+pub(crate) struct SimulatedExchange {
+    pub(crate) account: Account,
+    pub(crate) bid: rust_decimal::Decimal,
+    pub(crate) ask: rust_decimal::Decimal,
+    pub(crate) last: rust_decimal::Decimal,
+    resting_limit_orders: Vec<RestingOrder>,
+    resting_trigger_orders: Vec<RestingOrder>,
+    steps: VecDeque<OrderBookSnapshot>,
+}
+
+impl SimulatedExchange {
+    /// Build a simulated exchange from a replay of orderbook snapshots
+    pub(crate) fn new(steps: Vec<OrderBookSnapshot>, starting_cash: rust_decimal::Decimal) -> SimulatedExchange {
+        SimulatedExchange {
+            account: Account { cash: starting_cash, ..Account::default() },
+            bid: rust_decimal::Decimal::from(0),
+            ask: rust_decimal::Decimal::from(0),
+            last: rust_decimal::Decimal::from(0),
+            resting_limit_orders: Vec::new(),
+            resting_trigger_orders: Vec::new(),
+            steps: VecDeque::from(steps),
+        }
+    }
+
+    /// Whether the current bid/ask has crossed a resting trigger order.
+    fn trigger_crossed(order: &RestingOrder, bid: rust_decimal::Decimal, ask: rust_decimal::Decimal) -> bool {
+        match (order.side, order.kind) {
+            // Stop: fills as price moves adverse to the closed position.
+            (ftx::rest::Side::Sell, Some(TriggerKind::Stop)) => bid <= order.trigger_price,
+            (ftx::rest::Side::Buy, Some(TriggerKind::Stop)) => ask >= order.trigger_price,
+            // Take-profit: fills as price moves favorably for the closed position.
+            (ftx::rest::Side::Sell, Some(TriggerKind::TakeProfit)) => bid >= order.trigger_price,
+            (ftx::rest::Side::Buy, Some(TriggerKind::TakeProfit)) => ask <= order.trigger_price,
+            (ftx::rest::Side::Sell, None) => bid <= order.trigger_price,
+            (ftx::rest::Side::Buy, None) => ask >= order.trigger_price,
+        }
+    }
+
+    /// Advance the replay by one step, updating bid/ask/last and filling the
+    /// first resting take-profit/stop trigger the new prices have crossed.
+    pub(crate) fn advance(&mut self) -> Option<OrderBookSnapshot> {
+        let step = self.steps.pop_front()?;
+        self.bid = step.bid;
+        self.ask = step.ask;
+        self.last = (step.bid + step.ask) / rust_decimal::Decimal::from(2);
+
+        let (bid, ask) = (self.bid, self.ask);
+        if let Some(pos) = self.resting_trigger_orders.iter()
+            .position(|order| Self::trigger_crossed(order, bid, ask)) {
+            // Take-profit and stop-loss are placed together as an OCO
+            // bracket: once one leg fills, cancel every remaining trigger
+            // order (its sibling included) instead of letting both cross in
+            // the same step and close the position twice.
+            let filled = self.resting_trigger_orders.remove(pos);
+            self.resting_trigger_orders.clear();
+            self.fill(filled.side, filled.size, filled.trigger_price);
+            log::info!("[sim] Trigger order filled: {:?} {:?} at {:?}", filled.side, filled.size, filled.trigger_price);
+        }
+
+        Some(step)
+    }
+
+    fn fill(&mut self, side: ftx::rest::Side, size: rust_decimal::Decimal, price: rust_decimal::Decimal) {
+        let signed_size = match side {
+            ftx::rest::Side::Buy => size,
+            ftx::rest::Side::Sell => -size,
+        };
+        let old_position = self.account.position_size;
+        self.account.cash -= signed_size * price;
+
+        let zero = rust_decimal::Decimal::ZERO;
+        let same_direction = old_position == zero || (old_position > zero) == (signed_size > zero);
+        if same_direction {
+            // Opening or adding to the position: extend the weighted-average entry price.
+            let old_abs = old_position.abs();
+            let new_abs = old_abs + size;
+            self.account.avg_entry_price = (self.account.avg_entry_price * old_abs + price * size) / new_abs;
+        } else {
+            // Reducing, closing or reversing: realize PnL only on the
+            // portion of the fill that closes the existing position.
+            let closing_size = size.min(old_position.abs());
+            let direction = if old_position > zero { rust_decimal::Decimal::from(1) } else { rust_decimal::Decimal::from(-1) };
+            self.account.realized_pnl += direction * closing_size * (price - self.account.avg_entry_price);
+
+            let remaining = size - closing_size;
+            if remaining > zero {
+                // The fill was larger than the open position, so it flips
+                // side; the leftover opens a fresh position at this price.
+                self.account.avg_entry_price = price;
+            }
+        }
+
+        self.account.position_size = old_position + signed_size;
+    }
+
+    /// Final PnL/fill report for a completed backtest run.
+    pub(crate) fn report(&self) -> String {
+        format!(
+            "realized_pnl={:?}, ending_position={:?}, cash={:?}",
+            self.account.realized_pnl, self.account.position_size, self.account.cash
+        )
+    }
+}
+
+#[async_trait::async_trait]
+impl Exchange for SimulatedExchange {
+    async fn get_orderbook(&mut self, _market_name: &str, _depth: u32) -> Option<(rust_decimal::Decimal, rust_decimal::Decimal)> {
+        let step = self.advance()?;
+        Some((step.bid_volume, step.ask_volume))
+    }
+
+    async fn get_future_price(&mut self, _market_name: &str) -> Option<(rust_decimal::Decimal, rust_decimal::Decimal)> {
+        Some((self.bid, self.ask))
+    }
+
+    async fn get_open_position(&mut self, _market_name: &str) -> bool {
+        self.account.position_size != rust_decimal::Decimal::from(0)
+    }
+
+    async fn place_market_order(&mut self, _market_name: &str, side: ftx::rest::Side, size: rust_decimal::Decimal) -> bool {
+        let price = match side {
+            ftx::rest::Side::Buy => self.ask,
+            ftx::rest::Side::Sell => self.bid,
+        };
+        self.fill(side, size, price);
+        log::info!("[sim] Market order filled: {:?} {:?} at {:?}", side, size, price);
+        true
+    }
+
+    async fn place_limit_order(&mut self, _market_name: &str, side: ftx::rest::Side, size: rust_decimal::Decimal, price: rust_decimal::Decimal) -> bool {
+        if self.resting_limit_orders.len() >= MAX_RESTING_ORDERS {
+            log::warn!("[sim] Resting limit order book full, rejecting order");
+            return false;
+        }
+        self.resting_limit_orders.push(RestingOrder { side, size, trigger_price: price, kind: None });
+        true
+    }
+
+    async fn place_trigger_orders(&mut self, _market_name: &str, side: ftx::rest::Side, size: rust_decimal::Decimal, tp_price: rust_decimal::Decimal, sl_price: rust_decimal::Decimal) -> bool {
+        let trigger_side = crate::helpers::invert_side(side);
+        if self.resting_trigger_orders.len() + 2 > MAX_RESTING_ORDERS {
+            log::warn!("[sim] Resting trigger order book full, rejecting orders");
+            return false;
+        }
+        self.resting_trigger_orders.push(RestingOrder { side: trigger_side, size, trigger_price: tp_price, kind: Some(TriggerKind::TakeProfit) });
+        self.resting_trigger_orders.push(RestingOrder { side: trigger_side, size, trigger_price: sl_price, kind: Some(TriggerKind::Stop) });
+        true
+    }
+
+    async fn market_close_order(&mut self, _market_name: &str) -> bool {
+        if self.account.position_size == rust_decimal::Decimal::from(0) {
+            log::warn!("[sim] No order open, cannot close");
+            return false;
+        }
+        let side = if self.account.position_size > rust_decimal::Decimal::from(0) {
+            ftx::rest::Side::Sell
+        } else {
+            ftx::rest::Side::Buy
+        };
+        let size = self.account.position_size.abs();
+        self.place_market_order(_market_name, side, size).await
+    }
+
+    async fn cancel_all_trigger_orders(&mut self, _market_name: &str) -> bool {
+        self.resting_trigger_orders.clear();
+        true
+    }
+}
+
+/// Read historical orderbook snapshots from a CSV file with
+/// `bid,ask,bid_volume,ask_volume` columns.
+pub(crate) fn read_snapshots_from_csv(filepath: &str) -> Result<Vec<OrderBookSnapshot>, Box<dyn std::error::Error>> {
+    let mut rdr = csv::Reader::from_path(filepath)?;
+    let mut snapshots = Vec::new();
+    for result in rdr.records() {
+        let record = result?;
+        snapshots.push(OrderBookSnapshot {
+            bid: record[0].parse()?,
+            ask: record[1].parse()?,
+            bid_volume: record[2].parse()?,
+            ask_volume: record[3].parse()?,
+        });
+    }
+    Ok(snapshots)
+}
@@ -1,5 +1,8 @@
 //! A set of functions to handle config files, saving data and additional math
 
+pub(crate) mod bollinger;
+pub(crate) mod candles;
+
 /// Format to follow for settings JSON file
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
 pub(crate) struct SettingsFile {
@@ -9,8 +12,10 @@ pub(crate) struct SettingsFile {
     pub(crate) sampling_time: u64,
     /// Period of bollinger band
     pub(crate) bb_period: usize,
-    /// Standard deviation of bollinger band
-    pub(crate) bb_std_dev: f64,
+    /// Standard deviation of bollinger band. Kept as `Decimal` (rather than
+    /// `f64`) so the band thresholds that gate entries are computed
+    /// deterministically alongside the rest of the Decimal-based pipeline
+    pub(crate) bb_std_dev: rust_decimal::Decimal,
     /// Depth of orderbook to sum
     pub(crate) orderbook_depth: u32,
     /// Make live trades or not
@@ -23,17 +28,184 @@ pub(crate) struct SettingsFile {
     pub(crate) sl_percent: rust_decimal::Decimal,
     /// Store positions in csv (positions.csv by default)
     pub(crate) write_to_file: bool,
+    /// Whether entries are posted as "market" (cross the spread) or "limit"
+    /// (post maker at the current bid/ask)
+    #[serde(default)]
+    pub(crate) order_kind: OrderKind,
+    /// Path to a CSV of historical orderbook snapshots to replay through
+    /// `run_backtest` instead of connecting to the live FTX endpoint
+    #[serde(default)]
+    pub(crate) backtest_file: Option<String>,
+    /// Whether orderbook deltas are sourced by REST polling every
+    /// `sampling_time` seconds, or streamed over the FTX WebSocket as they
+    /// occur
+    #[serde(default)]
+    pub(crate) feed: Feed,
+    /// Width (in seconds) of each OHLC candle bucketed from sampled deltas,
+    /// written to candles_<market_name>.csv
+    #[serde(default = "default_candle_interval")]
+    pub(crate) candle_interval: u64,
+    /// Feed the Bollinger band candle-close deltas instead of raw samples
+    #[serde(default)]
+    pub(crate) bb_on_candle_close: bool,
+    /// Whether the stop loss is a static percentage offset, or a trailing
+    /// stop sized from recent volatility (see `StopMode`)
+    #[serde(default)]
+    pub(crate) stop_mode: StopMode,
+    /// Number of sampled mid-price moves averaged into the ATR estimate used
+    /// by `StopMode::Trailing`
+    #[serde(default = "default_atr_period")]
+    pub(crate) atr_period: usize,
+    /// Stop-loss distance (and trail value) as a multiple of ATR
+    #[serde(default = "default_atr_multiplier")]
+    pub(crate) atr_multiplier: rust_decimal::Decimal,
+    /// Take-profit distance as a multiple of the stop-loss distance
+    #[serde(default = "default_rr_ratio")]
+    pub(crate) rr_ratio: rust_decimal::Decimal,
+    /// Number of rows `CsvLogger` buffers before flushing to disk
+    #[serde(default = "default_csv_flush_interval")]
+    pub(crate) csv_flush_interval: usize,
+    /// Per-market overrides for running the strategy across several pairs
+    /// concurrently, sharing one exchange connection. When absent (or
+    /// empty), the bot runs a single market built from the legacy
+    /// `market_name`/`bb_period`/`bb_std_dev`/`orderbook_depth`/
+    /// `order_size`/`tp_percent`/`sl_percent` fields above.
+    #[serde(default)]
+    pub(crate) markets: Option<Vec<MarketConfig>>,
+    /// Cap on the size of a single entry order. This limits one entry at a
+    /// time, not cumulative exposure across markets or repeated entries into
+    /// the same market
+    #[serde(default = "default_max_position")]
+    pub(crate) max_position: rust_decimal::Decimal,
+    /// Minimum distance the delta must clear past the Bollinger band before
+    /// an entry fires, filtering out signals too weak to be worth crossing
+    /// the spread for
+    #[serde(default = "default_min_spread")]
+    pub(crate) min_spread: rust_decimal::Decimal,
+    /// When `true`, the bot only manages and exits existing positions and
+    /// never opens a new entry
+    #[serde(default)]
+    pub(crate) resume_only: bool,
+}
+
+/// Per-market tuning for one leg of a (possibly multi-market) run
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub(crate) struct MarketConfig {
+    pub(crate) market_name: String,
+    pub(crate) bb_period: usize,
+    pub(crate) bb_std_dev: rust_decimal::Decimal,
+    pub(crate) orderbook_depth: u32,
+    pub(crate) order_size: rust_decimal::Decimal,
+    pub(crate) tp_percent: rust_decimal::Decimal,
+    pub(crate) sl_percent: rust_decimal::Decimal,
+}
+
+impl SettingsFile {
+    /// Markets to run the strategy against concurrently: the explicit
+    /// `markets` list when present, otherwise a single `MarketConfig` built
+    /// from the legacy top-level fields
+    pub(crate) fn market_configs(&self) -> Vec<MarketConfig> {
+        match &self.markets {
+            Some(markets) if !markets.is_empty() => markets.clone(),
+            _ => vec![MarketConfig {
+                market_name: self.market_name.clone(),
+                bb_period: self.bb_period,
+                bb_std_dev: self.bb_std_dev,
+                orderbook_depth: self.orderbook_depth,
+                order_size: self.order_size,
+                tp_percent: self.tp_percent,
+                sl_percent: self.sl_percent,
+            }],
+        }
+    }
+}
+
+fn default_atr_period() -> usize {
+    14
+}
+
+fn default_atr_multiplier() -> rust_decimal::Decimal {
+    rust_decimal::Decimal::from(2)
+}
+
+fn default_rr_ratio() -> rust_decimal::Decimal {
+    rust_decimal::Decimal::from(2)
+}
+
+fn default_candle_interval() -> u64 {
+    60
+}
+
+fn default_csv_flush_interval() -> usize {
+    1
+}
+
+fn default_max_position() -> rust_decimal::Decimal {
+    rust_decimal::Decimal::MAX
+}
+
+fn default_min_spread() -> rust_decimal::Decimal {
+    rust_decimal::Decimal::ZERO
 }
 
 
 /// enum to store current position in market
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
 pub(crate) enum Side {
     Buy,
     Sell,
     None,
 }
 
+/// How an entry order should be placed on the exchange
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum OrderKind {
+    /// Cross the spread immediately
+    Market,
+    /// Post a maker order at the current bid/ask
+    Limit,
+}
+
+impl Default for OrderKind {
+    fn default() -> OrderKind {
+        OrderKind::Market
+    }
+}
+
+/// Where orderbook delta samples are sourced from
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Feed {
+    /// Poll `GetOrderBook` once every `sampling_time` seconds
+    Rest,
+    /// Subscribe to FTX's orderbook WebSocket channel
+    Websocket,
+}
+
+impl Default for Feed {
+    fn default() -> Feed {
+        Feed::Rest
+    }
+}
+
+/// How the stop loss for an open position is managed
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum StopMode {
+    /// Static `sl_percent`/`tp_percent` offsets from the entry price
+    Fixed,
+    /// ATR-sized stop that ratchets behind the position via `trail_value`
+    Trailing,
+}
+
+impl Default for StopMode {
+    fn default() -> StopMode {
+        StopMode::Fixed
+    }
+}
+
 impl std::fmt::Display for Side {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
@@ -50,54 +222,130 @@ impl Default for Side {
     }
 }
 
+/// A quantity denominated in a specific asset, e.g. an order size in the
+/// base currency or a price in the quote currency. Bundling the currency
+/// alongside the `Decimal` keeps base/quote amounts from being mixed up on
+/// the signal/order path once the bot trades more than one market.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Amount {
+    pub(crate) quantity: rust_decimal::Decimal,
+    pub(crate) currency: String,
+}
+
+impl Amount {
+    pub(crate) fn new(quantity: rust_decimal::Decimal, currency: String) -> Amount {
+        Amount { quantity, currency }
+    }
+
+    /// Convenience constructor for literal/config-derived `f64` quantities
+    pub(crate) fn from_f64(quantity: f64, currency: String) -> Amount {
+        Amount {
+            quantity: rust_decimal::prelude::FromPrimitive::from_f64(quantity).unwrap_or_default(),
+            currency,
+        }
+    }
+
+    /// Round `quantity` to `precision` decimal places, e.g. the precision
+    /// returned by `convert_increment_to_precision` for the market's
+    /// tick/lot size
+    pub(crate) fn round_to_precision(&self, precision: u32) -> Amount {
+        Amount {
+            quantity: self.quantity.round_dp(precision),
+            currency: self.currency.clone(),
+        }
+    }
+}
+
+impl std::fmt::Display for Amount {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} {}", self.quantity, self.currency)
+    }
+}
 
-/// Write utc time, price, size and current position to a csv file
-pub(crate) fn write_to_csv(
-    filename: &str,
-    price: rust_decimal::Decimal,
-    size: rust_decimal::Decimal,
-    side: &Side,
-    positions_count: usize) -> Result<(), Box<dyn std::error::Error>> {
-    let utc_time: chrono::prelude::DateTime<chrono::prelude::Utc> = chrono::prelude::Utc::now();
 
-    // Delete any existing file on first run
-    if positions_count == 1 as usize {
+/// Appends utc time, price, size and current position rows to a csv file.
+///
+/// Holds the `csv::Writer`/`BufWriter`/`File` open for as long as the logger
+/// lives, rather than reopening the file on every row, and reuses a single
+/// `csv::ByteRecord` rather than allocating a new row each call, so logging
+/// stays off the allocator on the hot path. Rows are buffered and flushed
+/// every `flush_interval` writes, and on `Drop` so a clean shutdown never
+/// loses buffered rows.
+pub(crate) struct CsvLogger {
+    writer: csv::Writer<std::io::BufWriter<std::fs::File>>,
+    record: csv::ByteRecord,
+    flush_interval: usize,
+    writes_since_flush: usize,
+}
+
+impl CsvLogger {
+    /// Open `filename` for append, truncating any existing file from a
+    /// previous run and writing the header row
+    pub(crate) fn new(filename: &str, flush_interval: usize) -> Result<CsvLogger, Box<dyn std::error::Error>> {
         let remove_file = std::fs::remove_file(filename);
-        match remove_file {
-            Err(_e) => {
-                log::info!("Positions file does not exist, creating new file")
-            }
-            Ok(o) => o
+        if let Err(_e) = remove_file {
+            log::info!("Positions file does not exist, creating new file")
         }
+
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(true)
+            .open(filename)?;
+        let mut writer = csv::Writer::from_writer(std::io::BufWriter::new(file));
+        writer.write_record(&[
+            "utc_time", "price", "price_currency", "size", "size_currency", "side",
+        ])?;
+        writer.flush()?;
+
+        Ok(CsvLogger {
+            writer,
+            record: csv::ByteRecord::new(),
+            flush_interval,
+            writes_since_flush: 0,
+        })
     }
 
-    // Append to existing file, or create new file
-    let file = std::fs::OpenOptions::new()
-        .write(true)
-        .create(true)
-        .append(true)
-        .open(String::from(filename))
-        .unwrap();
+    /// Append one row, flushing once `flush_interval` writes have
+    /// accumulated since the last flush
+    pub(crate) fn write_position(
+        &mut self,
+        price: &Amount,
+        size: &Amount,
+        side: &Side,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let utc_time: chrono::prelude::DateTime<chrono::prelude::Utc> = chrono::prelude::Utc::now();
 
-    log::debug!("Writing position to {:?}", String::from(filename));
+        self.record.clear();
+        self.record.push_field(utc_time.to_string().as_bytes());
+        self.record.push_field(price.quantity.to_string().as_bytes());
+        self.record.push_field(price.currency.as_bytes());
+        self.record.push_field(size.quantity.to_string().as_bytes());
+        self.record.push_field(size.currency.as_bytes());
+        self.record.push_field(side.to_string().as_bytes());
+        self.writer.write_byte_record(&self.record)?;
 
-    let mut wtr = csv::Writer::from_writer(file);
+        self.writes_since_flush += 1;
+        if self.writes_since_flush >= self.flush_interval {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Force any buffered rows out to disk
+    pub(crate) fn flush(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.writer.flush()?;
+        self.writes_since_flush = 0;
+        Ok(())
+    }
+}
 
-    // On first run, write header
-    if positions_count == 1 as usize {
-        wtr.write_record(&["utc_time", "price", "size", "side"])?;
+impl Drop for CsvLogger {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            log::warn!("Failed to flush positions csv on shutdown: {:?}", e);
+        }
     }
-    // Write row
-    wtr.write_record(
-        &[
-            utc_time.to_string(),
-            price.to_string(),
-            size.to_string(),
-            side.to_string()
-        ]
-    )?;
-    wtr.flush()?;
-    Ok(())
 }
 
 
@@ -129,6 +377,12 @@ pub(crate) fn read_settings(filepath: &str) -> SettingsFile {
     return settings;
 }
 
+/// Base currency traded by a market name, e.g. "BTC" for "BTC-PERP". Falls
+/// back to the full market name if it carries no "-" separated suffix.
+pub(crate) fn base_currency(market_name: &str) -> String {
+    market_name.split('-').next().unwrap_or(market_name).to_string()
+}
+
 /// Invert side i.e. buy -> sell, sell -> buy
 pub(crate) fn invert_side(side: ftx::rest::Side) -> ftx::rest::Side {
     return match side {
@@ -0,0 +1,90 @@
+//! Decimal-based Bollinger Band pipeline.
+//!
+//! The upstream `ta` crate's `BollingerBands` operates on `f64`, which means
+//! the band thresholds gating entries were computed in binary floating
+//! point even though order sizes and prices elsewhere already use
+//! `rust_decimal::Decimal`. This computes the same rolling mean/stddev
+//! entirely in `Decimal` so signals at the band boundary are reproducible.
+
+use std::collections::VecDeque;
+
+/// Mean and upper/lower band values for one step
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct BollingerOutput {
+    pub(crate) mean: rust_decimal::Decimal,
+    pub(crate) upper: rust_decimal::Decimal,
+    pub(crate) lower: rust_decimal::Decimal,
+}
+
+/// Smallest *relative* change in successive Newton's-method guesses before
+/// `decimal_sqrt` considers itself converged. A fixed absolute epsilon isn't
+/// representable once `guess` grows past a handful of digits (one Decimal
+/// ULP exceeds it), so convergence is checked relative to the guess instead.
+fn sqrt_epsilon() -> rust_decimal::Decimal {
+    rust_decimal::Decimal::new(1, 10)
+}
+
+/// Hard cap on Newton iterations, in case a value sits exactly on the
+/// boundary where successive guesses oscillate rather than converge
+const SQRT_MAX_ITERATIONS: u32 = 100;
+
+/// Square root of a non-negative `Decimal` via Newton's method:
+/// `g = (g + value/g) / 2`, iterated until the relative change drops below
+/// `sqrt_epsilon` or `SQRT_MAX_ITERATIONS` is reached.
+fn decimal_sqrt(value: rust_decimal::Decimal) -> rust_decimal::Decimal {
+    if value <= rust_decimal::Decimal::ZERO {
+        return rust_decimal::Decimal::ZERO;
+    }
+
+    let two = rust_decimal::Decimal::from(2);
+    let epsilon = sqrt_epsilon();
+    let mut guess = value;
+    for _ in 0..SQRT_MAX_ITERATIONS {
+        let next = (guess + value / guess) / two;
+        if next == rust_decimal::Decimal::ZERO || (next - guess).abs() / next < epsilon {
+            return next;
+        }
+        guess = next;
+    }
+    guess
+}
+
+/// Rolling Bollinger Band over the last `period` delta observations
+pub(crate) struct BollingerBands {
+    period: usize,
+    std_dev: rust_decimal::Decimal,
+    window: VecDeque<rust_decimal::Decimal>,
+}
+
+impl BollingerBands {
+    pub(crate) fn new(period: usize, std_dev: rust_decimal::Decimal) -> BollingerBands {
+        BollingerBands {
+            period,
+            std_dev,
+            window: VecDeque::with_capacity(period),
+        }
+    }
+
+    /// Feed the next delta observation into the rolling window and return
+    /// the updated mean/upper/lower band
+    pub(crate) fn next(&mut self, value: rust_decimal::Decimal) -> BollingerOutput {
+        if self.window.len() == self.period {
+            self.window.pop_front();
+        }
+        self.window.push_back(value);
+
+        let n = rust_decimal::Decimal::from(self.window.len() as i64);
+        let mean = self.window.iter().sum::<rust_decimal::Decimal>() / n;
+
+        let variance = self.window.iter()
+            .map(|x| (*x - mean) * (*x - mean))
+            .sum::<rust_decimal::Decimal>() / n;
+        let stddev = decimal_sqrt(variance);
+
+        BollingerOutput {
+            mean,
+            upper: mean + self.std_dev * stddev,
+            lower: mean - self.std_dev * stddev,
+        }
+    }
+}
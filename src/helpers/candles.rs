@@ -0,0 +1,119 @@
+//! Aggregates per-sample price/delta observations into fixed-interval OHLC
+//! candles, written to `candles_<market_name>.csv` alongside
+//! `positions_<market_name>.csv`.
+
+/// One completed OHLC candle over `interval` seconds
+#[derive(Debug, Clone)]
+pub(crate) struct Candle {
+    pub(crate) bucket_start: i64,
+    pub(crate) price_open: rust_decimal::Decimal,
+    pub(crate) price_high: rust_decimal::Decimal,
+    pub(crate) price_low: rust_decimal::Decimal,
+    pub(crate) price_close: rust_decimal::Decimal,
+    pub(crate) delta_open: rust_decimal::Decimal,
+    pub(crate) delta_high: rust_decimal::Decimal,
+    pub(crate) delta_low: rust_decimal::Decimal,
+    pub(crate) delta_close: rust_decimal::Decimal,
+    pub(crate) bid_volume: rust_decimal::Decimal,
+    pub(crate) ask_volume: rust_decimal::Decimal,
+}
+
+/// Batches per-sample price/delta observations into fixed `interval`-second
+/// buckets (floor of UTC now to the interval), emitting a completed `Candle`
+/// whenever a sample crosses into the next bucket
+pub(crate) struct CandleAggregator {
+    interval: i64,
+    current_bucket: Option<i64>,
+    candle: Option<Candle>,
+}
+
+impl CandleAggregator {
+    pub(crate) fn new(interval: u64) -> CandleAggregator {
+        CandleAggregator {
+            interval: interval as i64,
+            current_bucket: None,
+            candle: None,
+        }
+    }
+
+    /// Feed one sample in, returning the just-completed candle if this
+    /// sample crossed into a new bucket
+    pub(crate) fn sample(
+        &mut self,
+        utc_now: chrono::DateTime<chrono::Utc>,
+        price: rust_decimal::Decimal,
+        delta: rust_decimal::Decimal,
+        bid_volume: rust_decimal::Decimal,
+        ask_volume: rust_decimal::Decimal,
+    ) -> Option<Candle> {
+        let bucket_start = (utc_now.timestamp() / self.interval) * self.interval;
+
+        if self.current_bucket != Some(bucket_start) {
+            let completed = self.candle.take();
+            self.current_bucket = Some(bucket_start);
+            self.candle = Some(Candle {
+                bucket_start,
+                price_open: price,
+                price_high: price,
+                price_low: price,
+                price_close: price,
+                delta_open: delta,
+                delta_high: delta,
+                delta_low: delta,
+                delta_close: delta,
+                bid_volume,
+                ask_volume,
+            });
+            return completed;
+        }
+
+        if let Some(candle) = &mut self.candle {
+            candle.price_high = candle.price_high.max(price);
+            candle.price_low = candle.price_low.min(price);
+            candle.price_close = price;
+            candle.delta_high = candle.delta_high.max(delta);
+            candle.delta_low = candle.delta_low.min(delta);
+            candle.delta_close = delta;
+            candle.bid_volume += bid_volume;
+            candle.ask_volume += ask_volume;
+        }
+        None
+    }
+}
+
+/// Append a completed candle to the csv at `filename`, writing the header on first use
+pub(crate) fn write_candle_to_csv(
+    filename: &str,
+    candle: &Candle,
+    candles_count: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .append(true)
+        .open(filename)?;
+
+    let mut wtr = csv::Writer::from_writer(file);
+
+    if candles_count == 1 as usize {
+        wtr.write_record(&[
+            "bucket_start", "price_open", "price_high", "price_low", "price_close",
+            "delta_open", "delta_high", "delta_low", "delta_close", "bid_volume", "ask_volume",
+        ])?;
+    }
+    wtr.write_record(&[
+        candle.bucket_start.to_string(),
+        candle.price_open.to_string(),
+        candle.price_high.to_string(),
+        candle.price_low.to_string(),
+        candle.price_close.to_string(),
+        candle.delta_open.to_string(),
+        candle.delta_high.to_string(),
+        candle.delta_low.to_string(),
+        candle.delta_close.to_string(),
+        candle.bid_volume.to_string(),
+        candle.ask_volume.to_string(),
+    ])?;
+    wtr.flush()?;
+    Ok(())
+}
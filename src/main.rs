@@ -12,10 +12,62 @@
 //! A full analysis of this strategy is given in
 //! [dineshpinto/market-analytics](https://github.com/dineshpinto/market-analytics)
 
+mod cli;
+mod exchange;
+mod feed;
 mod helpers;
 mod order_handler;
+mod order_state;
 mod tests;
 
+/// Run the delta/Bollinger strategy against a replay of historical orderbook
+/// snapshots instead of the live FTX endpoint, printing a final PnL/fill
+/// report. This lets `bb_period`, `bb_std_dev`, `tp_percent` and `sl_percent`
+/// be tuned without risking capital.
+async fn run_backtest(settings: &helpers::SettingsFile, snapshots: Vec<exchange::OrderBookSnapshot>) {
+    use exchange::Exchange;
+
+    let mut sim = exchange::SimulatedExchange::new(snapshots, rust_decimal::Decimal::from(0));
+    let mut bb = helpers::bollinger::BollingerBands::new(settings.bb_period, settings.bb_std_dev);
+
+    let mut count: usize = 0;
+    let mut current_side = helpers::Side::default();
+
+    while let Some((bid_volume, ask_volume)) = sim.get_orderbook(&settings.market_name, settings.orderbook_depth).await {
+        count += 1;
+        let perp_delta = bid_volume - ask_volume;
+        let out = bb.next(perp_delta);
+
+        if count <= settings.bb_period {
+            continue;
+        }
+
+        if perp_delta > out.upper || perp_delta < out.lower {
+            let side = if perp_delta > out.upper { helpers::Side::Sell } else { helpers::Side::Buy };
+            if side == current_side {
+                continue;
+            }
+            current_side = side;
+
+            let order_side = if side == helpers::Side::Buy { ftx::rest::Side::Buy } else { ftx::rest::Side::Sell };
+            let price = if order_side == ftx::rest::Side::Buy { sim.ask } else { sim.bid };
+
+            if sim.get_open_position(&settings.market_name).await {
+                sim.market_close_order(&settings.market_name).await;
+                sim.cancel_all_trigger_orders(&settings.market_name).await;
+            }
+
+            sim.place_market_order(&settings.market_name, order_side, settings.order_size).await;
+
+            let (tp_price, sl_price) = order_handler::calculate_tp_and_sl(
+                price, order_side, settings.tp_percent, settings.sl_percent, 2);
+            sim.place_trigger_orders(&settings.market_name, order_side, settings.order_size, tp_price, sl_price).await;
+        }
+    }
+
+    log::info!("Backtest complete over {:?} steps: {}", count, sim.report());
+}
+
 /// Core logical loop for the bot.
 ///
 /// The process is:
@@ -30,10 +82,6 @@ mod tests;
 ///
 #[tokio::main]
 async fn main() {
-    // Load settings file
-    let settings_filepath = String::from("settings.json");
-    let settings = helpers::read_settings(&settings_filepath);
-
     // Set up logging
     let mut builder = env_logger::Builder::new();
     builder
@@ -42,9 +90,55 @@ async fn main() {
         .target(env_logger::Target::Stdout)
         .init();
 
+    let args = <cli::Cli as clap::Parser>::parse();
+
+    match args.command {
+        cli::Command::Run { config } => run(config).await,
+        cli::Command::Position => {
+            let api = cli::connect_live_api();
+            let positions = api.request(ftx::rest::GetPositions {}).await.unwrap();
+            for position in positions {
+                log::info!("{:?}", position);
+            }
+        }
+        cli::Command::Close { market } => {
+            let api = cli::connect_live_api();
+            order_handler::market_close_order(&api, &market).await;
+        }
+        cli::Command::Cancel { market } => {
+            let api = cli::connect_live_api();
+            order_handler::cancel_all_trigger_orders(&api, &market).await;
+        }
+        cli::Command::Order { market, side, size } => {
+            let api = cli::connect_live_api();
+            order_handler::place_market_order(&api, order_handler::NewMarketOrder {
+                market,
+                side: side.into(),
+                size,
+            }).await;
+        }
+    }
+}
+
+/// Run the delta/Bollinger strategy loop, reading settings from `config`
+async fn run(config: String) {
+    // Load settings file
+    let settings_filepath = config;
+    let settings = helpers::read_settings(&settings_filepath);
+
     log::info!("Settings file loaded from {:?}.", settings_filepath);
     log::info!("{:?}", settings);
 
+    // If a backtest file is configured, replay it against a SimulatedExchange
+    // instead of connecting to the live FTX endpoint
+    if let Some(backtest_file) = &settings.backtest_file {
+        log::info!("Running backtest against {:?}", backtest_file);
+        let snapshots = exchange::read_snapshots_from_csv(backtest_file)
+            .expect("Unable to read backtest snapshots");
+        run_backtest(&settings, snapshots).await;
+        return;
+    }
+
     // Set up connection to FTX API
     let api = if settings.live {
         // Read .env file for API keys if bot is live
@@ -62,11 +156,36 @@ async fn main() {
         )
     };
 
+    // Share both the REST connection and the settings across one strategy
+    // task per configured market
+    let api = std::sync::Arc::new(api);
+    let settings = std::sync::Arc::new(settings);
+    let markets = settings.market_configs();
+
+    let tasks: Vec<_> = markets.into_iter().map(|market| {
+        let api = api.clone();
+        let settings = settings.clone();
+        tokio::spawn(run_market(api, settings, market))
+    }).collect();
+
+    for task in tasks {
+        task.await.expect("Strategy task panicked");
+    }
+}
+
+/// Run the delta/Bollinger strategy loop for a single `market`, sharing the
+/// `api` connection and global risk settings with any other markets running
+/// concurrently
+async fn run_market(
+    api: std::sync::Arc<ftx::rest::Rest>,
+    settings: std::sync::Arc<helpers::SettingsFile>,
+    market: helpers::MarketConfig,
+) {
     // Get precision for price and size for current market,
     // use MidpointNearestEven rounding (Banker's rounding)
     let future_result = api.request(
         ftx::rest::GetFuture {
-            future_name: String::from(&settings.market_name)
+            future_name: String::from(&market.market_name)
         }
     ).await.unwrap();
 
@@ -77,10 +196,10 @@ async fn main() {
     // Set precision for order
 
     // Panic if order size is too small
-    if settings.order_size < future_result.size_increment {
+    if market.order_size < future_result.size_increment {
         log::error!(
             "Order size is smaller than minimum order size ({:?} < {:?})",
-            settings.order_size, future_result.size_increment
+            market.order_size, future_result.size_increment
         );
         panic!();
     }
@@ -89,72 +208,140 @@ async fn main() {
     if future_result.size_increment < rust_decimal::Decimal::from(1) {
         let size_precision = helpers::convert_increment_to_precision(
             future_result.size_increment);
-        _order_size = settings.order_size.round_dp(size_precision);
+        _order_size = market.order_size.round_dp(size_precision);
     } else {
-        _order_size = (future_result.size_increment * settings.order_size).round()
+        _order_size = (future_result.size_increment * market.order_size).round()
             / future_result.size_increment;
     }
 
+    // max_position caps the size of a single entry; it is not a portfolio-wide
+    // exposure limit, so it says nothing about cumulative size across markets
+    // or repeated entries into the same market
+    if _order_size > settings.max_position {
+        log::warn!(
+            "Order size {:?} exceeds max_position {:?}, capping",
+            _order_size, settings.max_position
+        );
+        _order_size = settings.max_position;
+    }
+
     // Set up bollinger bands
-    let mut bb = ta::indicators::BollingerBands::new(
-        settings.bb_period,
-        settings.bb_std_dev,
-    ).unwrap();
+    let mut bb = helpers::bollinger::BollingerBands::new(market.bb_period, market.bb_std_dev);
 
     // Set up loop outer variables
     let mut count: usize = 0;
     let mut positions_count: usize = 0;
+    let mut candles_count: usize = 0;
     let mut current_side: helpers::Side = helpers::Side::default();
     let mut price = rust_decimal::Decimal::default();
+    let mut candle_agg = helpers::candles::CandleAggregator::new(settings.candle_interval);
+    let mut atr_tracker = order_handler::AtrTracker::new(settings.atr_period);
+    let mut positions_logger = if settings.write_to_file {
+        Some(helpers::CsvLogger::new(
+            &format!("positions_{}.csv", market.market_name), settings.csv_flush_interval,
+        ).expect("Unable to open positions csv"))
+    } else {
+        None
+    };
 
-    log::info!("Setting trigger in {:?} iterations (approx {:?}s)...",
-        settings.bb_period,
-        settings.bb_period as u64 * settings.time_delta
+    // Resume a persisted order across restarts, rather than double-entering
+    // a position or forgetting an armed stop
+    let order_state_filepath = order_state::order_state_filepath(&market.market_name);
+    let mut current_order: Option<order_state::Order> = order_state::load_order_state(&order_state_filepath);
+    if let Some(order) = &current_order {
+        log::info!("Resuming persisted order for {:?}: {:?}", market.market_name, order);
+        current_side = order.side;
+    }
+
+    log::info!("[{:?}] Setting trigger in {:?} iterations (approx {:?}s)...",
+        market.market_name,
+        market.bb_period,
+        market.bb_period as u64 * settings.sampling_time
     );
 
-    loop {
+    // Spawn the configured orderbook feed, which pushes delta samples onto
+    // `rx` either every `sampling_time` seconds (REST polling) or as
+    // websocket updates arrive
+    let (tx, mut rx) = tokio::sync::mpsc::channel(32);
+    match settings.feed {
+        helpers::Feed::Rest => {
+            tokio::spawn(feed::run_rest_feed(
+                api.clone(),
+                String::from(&market.market_name),
+                market.orderbook_depth,
+                settings.sampling_time,
+                tx,
+            ));
+        }
+        helpers::Feed::Websocket => {
+            tokio::spawn(feed::run_websocket_feed(
+                String::from(&market.market_name),
+                tx,
+            ));
+        }
+    }
+
+    while let Some(sample) = rx.recv().await {
+        // Batch the sample's price/delta into the current OHLC candle,
+        // writing it to candles_<market_name>.csv once a new bucket is entered
+        let mid_price = (sample.bid_price + sample.ask_price) / rust_decimal::Decimal::from(2);
+        let atr = atr_tracker.update(mid_price);
+        let completed_candle = candle_agg.sample(
+            chrono::Utc::now(), mid_price, sample.perp_delta, sample.bid_volume, sample.ask_volume);
+        if let Some(candle) = &completed_candle {
+            candles_count += 1;
+            helpers::candles::write_candle_to_csv(&format!("candles_{}.csv", market.market_name), candle, candles_count)
+                .expect("Unable to write candle to file.");
+        }
+
+        // When configured, only analyze on candle close rather than every raw sample
+        if settings.bb_on_candle_close && completed_candle.is_none() {
+            continue;
+        }
+
         count += 1;
-        // Sleep before loop logic to handle continue statements
-        std::thread::sleep(std::time::Duration::from_secs(settings.time_delta));
-
-        // Get orderbook
-        let order_book = api.request(
-            ftx::rest::GetOrderBook {
-                market_name: String::from(&settings.market_name),
-                depth: Option::from(settings.orderbook_depth),
-            }
-        ).await;
-        let order_book = match order_book {
-            Err(e) => {
-                // Continue loop is getting orderbook fails
-                log::error!("Error: {:?}", e);
-                continue;
-            }
-            Ok(o) => o
-        };
 
         // Calculate values used for analysis
-        let perp_delta = rust_decimal::prelude::ToPrimitive::to_f64(
-            &(order_book.bids[0].1 - order_book.asks[0].1)).unwrap();
-        let out = ta::Next::next(&mut bb, perp_delta);
+        let perp_delta = match (&completed_candle, settings.bb_on_candle_close) {
+            (Some(candle), true) => candle.delta_close,
+            _ => sample.perp_delta,
+        };
+        let out = bb.next(perp_delta);
         let bb_lower = out.lower;
         let bb_upper = out.upper;
 
-        log::debug!("perp_delta={:.2}, bb_lower={:.2}, bb_upper={:.2}",
+        log::debug!("perp_delta={:?}, bb_lower={:?}, bb_upper={:?}",
             perp_delta, bb_lower, bb_upper);
 
         // Only perform further calculation if bb_period is passed
-        if count > settings.bb_period {
-            if count == settings.bb_period + 1 {
+        if count > market.bb_period {
+            if count == market.bb_period + 1 {
                 log::info!("Trigger is now set...")
             }
 
             // Entry conditions
             if perp_delta > bb_upper || perp_delta < bb_lower {
+                // Require the delta to clear the band by at least
+                // `min_spread` before treating it as a tradeable signal,
+                // filtering out breaches too small to be worth crossing the
+                // spread for
+                let band_distance = if perp_delta > bb_upper {
+                    perp_delta - bb_upper
+                } else {
+                    bb_lower - perp_delta
+                };
+                if band_distance < settings.min_spread {
+                    log::debug!(
+                        "Band distance {:?} below min_spread {:?}, skipping signal",
+                        band_distance, settings.min_spread
+                    );
+                    continue;
+                }
+
                 // Get current price
                 let price_result = api.request(
                     ftx::rest::GetFuture {
-                        future_name: String::from(&settings.market_name)
+                        future_name: String::from(&market.market_name)
                     }
                 ).await;
                 let (bid_price, ask_price) = match price_result {
@@ -205,62 +392,151 @@ async fn main() {
                     continue;
                 };
 
-                // Calculate static TP and SL for order
-                // TODO: Use dynamic TP and SL based on market movements
-                let (tp_price, sl_price) = order_handler::calculate_tp_and_sl(
-                    price, order_side, settings.tp_percent, settings.sl_percent, price_precision);
+                // Calculate TP and SL for the order: a static percentage
+                // offset, or an ATR-sized trailing stop with TP kept at a
+                // configurable reward:risk via rr_ratio
+                let (tp_price, sl_price, trail_value) = match (settings.stop_mode, atr) {
+                    (helpers::StopMode::Trailing, Some(atr)) => {
+                        let (tp, sl, sl_distance) = order_handler::calculate_dynamic_tp_and_sl(
+                            price, order_side, atr, settings.atr_multiplier, settings.rr_ratio, price_precision);
+                        (tp, sl, Some(sl_distance))
+                    }
+                    _ => {
+                        let (tp, sl) = order_handler::calculate_tp_and_sl(
+                            price, order_side, market.tp_percent, market.sl_percent, price_precision);
+                        (tp, sl, None)
+                    }
+                };
+                let order_size_amount = helpers::Amount::new(_order_size, helpers::base_currency(&market.market_name));
+                let price_amount = helpers::Amount::new(price, "USD".to_string());
                 log::info!(
-                    "{:?} {:?} {} at {:?}. Take profit at {:?} ({:?}%) and \
-                    stop loss at {:?} ({:?}%)",
-                    current_side, _order_size, settings.market_name, price, tp_price,
-                    settings.tp_percent, sl_price, settings.sl_percent
+                    "{:?} {} {} at {}. Take profit at {:?} and stop loss at {:?} \
+                    (trail_value={:?})",
+                    current_side, order_size_amount, market.market_name, price_amount, tp_price,
+                    sl_price, trail_value
                 );
                 positions_count += 1;
 
                 if settings.live {
                     // Check if position is currently open and close it
                     let open_position = futures::executor::block_on(
-                        order_handler::get_open_position(&api, &settings.market_name));
+                        order_handler::get_open_position(&api, &market.market_name));
 
                     if open_position {
                         log::info!("Closing existing position...");
                         futures::executor::block_on(
                             order_handler::market_close_order(
-                                &api, &settings.market_name,
+                                &api, &market.market_name,
                             )
                         );
                         futures::executor::block_on(
                             order_handler::cancel_all_trigger_orders(
-                                &api, &settings.market_name,
+                                &api, &market.market_name,
                             )
                         );
+
+                        if let Some(mut order) = current_order.take() {
+                            order.transition(order_state::OrderState::Filled);
+                            order_state::save_order_state(&order_state_filepath, Some(&order)).ok();
+                        }
+                    }
+
+                    // resume_only manages/exits existing positions only;
+                    // never opens a new entry
+                    if settings.resume_only {
+                        log::info!(
+                            "resume_only is set, skipping new entry for {:?}",
+                            market.market_name
+                        );
+                        continue;
                     }
 
                     // TODO: Use Kelly criterion for order sizing
-                    // Place order on FTX
-                    let order_placed = futures::executor::block_on(
-                        order_handler::place_market_order(
-                            &api,
-                            &settings.market_name,
-                            order_side,
-                            _order_size,
-                        )
-                    );
+                    // Place order on FTX, posting a maker limit order at the
+                    // current bid/ask if configured, otherwise crossing the
+                    // spread with a market order. Neither is guaranteed to
+                    // fill in full (a maker order can rest unfilled, a taker
+                    // order can partially fill on a thin book), so the
+                    // actual filled size is reconciled before sizing the
+                    // triggers below.
+                    let filled_size = match settings.order_kind {
+                        helpers::OrderKind::Limit => {
+                            // A post_only order must rest on the maker side
+                            // of the book, the opposite of the taker price
+                            // used for TP/SL: a sell posts at the ask, a
+                            // buy posts at the bid.
+                            let limit_price = if order_side == ftx::rest::Side::Sell { ask_price } else { bid_price };
+                            let order_id = futures::executor::block_on(
+                                order_handler::place_limit_order(
+                                    &api,
+                                    order_handler::NewLimitOrder {
+                                        market: String::from(&market.market_name),
+                                        side: order_side,
+                                        size: _order_size,
+                                        price: limit_price,
+                                        post_only: true,
+                                    },
+                                )
+                            );
+                            match order_id {
+                                None => rust_decimal::Decimal::from(0),
+                                Some(order_id) => futures::executor::block_on(
+                                    order_handler::reconcile_filled_size(
+                                        &api, &market.market_name, order_id, _order_size,
+                                    )
+                                ),
+                            }
+                        }
+                        helpers::OrderKind::Market => {
+                            let order_id = futures::executor::block_on(
+                                order_handler::place_market_order(
+                                    &api,
+                                    order_handler::NewMarketOrder {
+                                        market: String::from(&market.market_name),
+                                        side: order_side,
+                                        size: _order_size,
+                                    },
+                                )
+                            );
+                            match order_id {
+                                None => rust_decimal::Decimal::from(0),
+                                Some(order_id) => futures::executor::block_on(
+                                    order_handler::reconcile_filled_size(
+                                        &api, &market.market_name, order_id, _order_size,
+                                    )
+                                ),
+                            }
+                        }
+                    };
 
-                    if !order_placed {
-                        log::warn!("Unable to place order, will continue with loop...");
+                    if filled_size == rust_decimal::Decimal::from(0) {
+                        log::warn!("Order did not fill, will continue with loop...");
                         continue;
                     }
+                    if filled_size != _order_size {
+                        log::warn!(
+                            "Requested size {:?} but only {:?} filled, sizing triggers to the fill",
+                            _order_size, filled_size
+                        );
+                    }
 
-                    // Place trigger orders on FTX
+                    let mut order = order_state::Order::new(
+                        current_side, price, filled_size, tp_price, sl_price,
+                    );
+                    order.transition(order_state::OrderState::Open);
+                    order_state::save_order_state(&order_state_filepath, Some(&order)).ok();
+
+                    // Place trigger orders on FTX, sized to the actual filled
+                    // quantity rather than the requested order size
                     let triggers_placed = futures::executor::block_on(
                         order_handler::place_trigger_orders(
                             &api,
-                            &settings.market_name,
+                            &market.market_name,
                             order_side,
-                            _order_size,
+                            filled_size,
                             tp_price,
                             sl_price,
+                            trail_value,
                         )
                     );
 
@@ -269,30 +545,39 @@ async fn main() {
                     if !triggers_placed {
                         log::warn!("Cancelling all orders...");
                         let order_closed = futures::executor::block_on(
-                            order_handler::market_close_order(&api, &settings.market_name));
+                            order_handler::market_close_order(&api, &market.market_name));
                         let triggers_cancelled = futures::executor::block_on(
                             order_handler::cancel_all_trigger_orders(
-                                &api, &settings.market_name,
+                                &api, &market.market_name,
                             )
                         );
 
                         if order_closed && triggers_cancelled {
+                            order.transition(order_state::OrderState::Cancelled);
+                            order_state::save_order_state(&order_state_filepath, Some(&order)).ok();
                             continue;
                         } else {
                             log::error!("Unable to close order, panicking!");
                             panic!()
                         }
                     }
+
+                    // Both triggers are placed together by place_trigger_orders,
+                    // so the state walks through TakeProfitArmed to
+                    // StopLossArmed in one go rather than persisting the
+                    // intermediate state
+                    order.transition(order_state::OrderState::TakeProfitArmed);
+                    order.transition(order_state::OrderState::StopLossArmed);
+                    order_state::save_order_state(&order_state_filepath, Some(&order)).ok();
+                    current_order = Some(order);
                 }
 
                 // Write the positions to a csv
-                if settings.write_to_file {
-                    helpers::write_to_csv(
-                        "positions.csv",
-                        price,
-                        _order_size,
+                if let Some(logger) = &mut positions_logger {
+                    logger.write_position(
+                        &price_amount,
+                        &order_size_amount,
                         &current_side,
-                        positions_count,
                     ).expect("Unable to write positions to file.");
                 }
             }
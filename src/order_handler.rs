@@ -1,38 +1,155 @@
 //! A set of functions to handle placing market or limit orders,
 //! trigger orders and canceling orders
 
-/// Create a market order on FTX
+/// Parameters for a market order that crosses the spread immediately.
+///
+/// Unlike a limit order, a market order has no notion of a resting price,
+/// so it does not carry a `price` field.
+pub(crate) struct NewMarketOrder {
+    pub(crate) market: String,
+    pub(crate) side: ftx::rest::Side,
+    pub(crate) size: rust_decimal::Decimal,
+}
+
+impl NewMarketOrder {
+    fn into_place_order(self) -> ftx::rest::PlaceOrder {
+        ftx::rest::PlaceOrder {
+            market: self.market,
+            side: self.side,
+            price: None,
+            r#type: ftx::rest::OrderType::Market,
+            size: self.size,
+            reduce_only: false,
+            ioc: false,
+            post_only: false,
+            client_id: None,
+            reject_on_price_band: false,
+        }
+    }
+}
+
+/// Parameters for a maker limit order posted at a specific `price`.
+pub(crate) struct NewLimitOrder {
+    pub(crate) market: String,
+    pub(crate) side: ftx::rest::Side,
+    pub(crate) size: rust_decimal::Decimal,
+    pub(crate) price: rust_decimal::Decimal,
+    pub(crate) post_only: bool,
+}
+
+impl NewLimitOrder {
+    fn into_place_order(self) -> ftx::rest::PlaceOrder {
+        ftx::rest::PlaceOrder {
+            market: self.market,
+            side: self.side,
+            price: Some(self.price),
+            r#type: ftx::rest::OrderType::Limit,
+            size: self.size,
+            reduce_only: false,
+            ioc: false,
+            post_only: self.post_only,
+            client_id: None,
+            reject_on_price_band: false,
+        }
+    }
+}
+
+/// Create a market order on FTX, returning its order id on success so the
+/// fill can be reconciled with `reconcile_filled_size` before placing
+/// triggers against it
 pub(crate) async fn place_market_order(
     api: &ftx::rest::Rest,
-    market_name: &str,
-    order_side: ftx::rest::Side,
-    order_size: rust_decimal::Decimal) -> bool {
-    let order = api.request(ftx::rest::PlaceOrder {
-        market: std::string::String::from(market_name),
-        side: order_side,
-        price: None,
-        r#type: ftx::rest::OrderType::Market,
-        size: order_size,
-        reduce_only: false,
-        ioc: false,
-        post_only: false,
-        client_id: None,
-        reject_on_price_band: false,
-    }).await;
+    order: NewMarketOrder) -> Option<u64> {
+    let order = api.request(order.into_place_order()).await;
 
-    let order_success;
     match order {
         Err(e) => {
             log::error!("Unable to place order, Err: {:?}", e);
-            order_success = false;
+            None
         }
         Ok(o) => {
             log::info!("Order placed successfully: {:?}", o);
-            order_success = true;
+            Some(o.id)
         }
-    };
+    }
+}
+
+/// Maximum time to wait for an entry order (market or limit) to fill before
+/// giving up
+const FILL_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+/// Interval between fill polls while waiting on `FILL_TIMEOUT`
+const FILL_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(200);
+
+/// Poll fills for `order_id`, summing trade quantities per order, until the
+/// requested size has filled or `FILL_TIMEOUT` elapses. A large order on a
+/// thin book can fill partially, so the returned size (which may be less
+/// than `requested_size`, or zero) should be used to size subsequent
+/// `place_trigger_orders` calls rather than assuming a full fill.
+pub(crate) async fn reconcile_filled_size(
+    api: &ftx::rest::Rest,
+    market_name: &str,
+    order_id: u64,
+    requested_size: rust_decimal::Decimal) -> rust_decimal::Decimal {
+    let deadline = std::time::Instant::now() + FILL_TIMEOUT;
+
+    loop {
+        let fills = api.request(ftx::rest::GetFills {
+            market: Option::from(String::from(market_name)),
+            ..Default::default()
+        }).await;
+
+        let filled_size = match fills {
+            Err(e) => {
+                log::error!("Unable to get fills, Err: {:?}", e);
+                rust_decimal::Decimal::from(0)
+            }
+            Ok(fills) => fills.iter()
+                .filter(|fill| fill.order_id == Option::from(order_id))
+                .map(|fill| fill.size)
+                .sum(),
+        };
+
+        if filled_size >= requested_size || std::time::Instant::now() >= deadline {
+            if filled_size != requested_size {
+                log::warn!(
+                    "Requested {:?} but only filled {:?} within timeout",
+                    requested_size, filled_size
+                );
+            }
+            if filled_size == rust_decimal::Decimal::from(0) {
+                log::warn!("Nothing filled within timeout, cancelling order {:?}", order_id);
+                if let Err(e) = api.request(ftx::rest::CancelOrder { order_id }).await {
+                    log::error!("Unable to cancel unfilled order {:?}, Err: {:?}", order_id, e);
+                }
+            }
+            return filled_size;
+        }
+
+        tokio::time::sleep(FILL_POLL_INTERVAL).await;
+    }
+}
+
+/// Create a maker limit order on FTX, posted at `order.price`, returning its
+/// order id on success so the fill can be reconciled with
+/// `reconcile_filled_size` before placing triggers against it. A `post_only`
+/// order can rest unfilled indefinitely, so the caller must not assume a
+/// full fill just because placement succeeded.
+pub(crate) async fn place_limit_order(
+    api: &ftx::rest::Rest,
+    order: NewLimitOrder) -> Option<u64> {
+    let price = order.price;
+    let order = api.request(order.into_place_order()).await;
 
-    return order_success;
+    match order {
+        Err(e) => {
+            log::error!("Unable to place limit order, Err: {:?}", e);
+            None
+        }
+        Ok(o) => {
+            log::info!("Limit order placed successfully at {:?}: {:?}", price, o);
+            Some(o.id)
+        }
+    }
 }
 
 /// Check if position is open on a market
@@ -55,18 +172,11 @@ pub(crate) async fn market_close_order(api: &ftx::rest::Rest, market_name: &str)
 
     for position in positions {
         if position.future == market_name {
-            let order_closed = api.request(ftx::rest::PlaceOrder {
+            let order_closed = api.request(NewMarketOrder {
                 market: String::from(market_name),
                 side: crate::helpers::invert_side(position.side),
-                price: None,
-                r#type: ftx::rest::OrderType::Market,
                 size: position.size,
-                reduce_only: false,
-                ioc: false,
-                post_only: false,
-                client_id: None,
-                reject_on_price_band: false,
-            }).await;
+            }.into_place_order()).await;
 
             return match order_closed {
                 Err(e) => {
@@ -105,14 +215,19 @@ pub(crate) async fn cancel_all_trigger_orders(api: &ftx::rest::Rest, market_name
     };
 }
 
-/// Place take profit and stop loss orders
+/// Place take profit and stop loss orders.
+///
+/// `trail_value` is `None` for a static stop at `sl_price`, or
+/// `Some(distance)` to place the stop loss as a trailing stop that ratchets
+/// behind the position by `distance` instead of sitting at a fixed price.
 pub(crate) async fn place_trigger_orders(
     api: &ftx::rest::Rest,
     market_name: &str,
     order_side: ftx::rest::Side,
     order_size: rust_decimal::Decimal,
     tp_price: rust_decimal::Decimal,
-    sl_price: rust_decimal::Decimal) -> bool {
+    sl_price: rust_decimal::Decimal,
+    trail_value: Option<rust_decimal::Decimal>) -> bool {
     let trigger_side = match order_side {
         ftx::rest::Side::Buy => ftx::rest::Side::Sell,
         ftx::rest::Side::Sell => ftx::rest::Side::Buy,
@@ -123,7 +238,7 @@ pub(crate) async fn place_trigger_orders(
         side: trigger_side,
         size: order_size,
         r#type: ftx::rest::OrderType::TakeProfit,
-        trigger_price: tp_price,
+        trigger_price: Some(tp_price),
         reduce_only: Option::from(true),
         retry_until_filled: None,
         order_price: None,
@@ -141,16 +256,19 @@ pub(crate) async fn place_trigger_orders(
         }
     };
 
+    // A trailing stop is its own FTX order type keyed off trail_value with
+    // no trigger_price; a fixed stop is keyed off trigger_price and can't
+    // carry a trail_value. The two are mutually exclusive on the wire.
     let stop_loss = api.request(ftx::rest::PlaceTriggerOrder {
         market: String::from(market_name),
         side: trigger_side,
         size: order_size,
-        r#type: ftx::rest::OrderType::Stop,
-        trigger_price: sl_price,
+        r#type: if trail_value.is_some() { ftx::rest::OrderType::TrailingStop } else { ftx::rest::OrderType::Stop },
+        trigger_price: if trail_value.is_some() { None } else { Some(sl_price) },
         reduce_only: Option::from(true),
         retry_until_filled: None,
         order_price: None,
-        trail_value: None,
+        trail_value,
     }).await;
 
     let stop_loss_success = match stop_loss {
@@ -187,3 +305,65 @@ pub(crate) fn calculate_tp_and_sl(
     };
     return (tp_price.round_dp(price_precision), sl_price.round_dp(price_precision));
 }
+
+/// Rolling average true range over the last `period` moves between sampled
+/// mid-prices,
+/// approximated from successive bid/ask mid changes since individual
+/// high/low candles aren't available from raw orderbook samples
+pub(crate) struct AtrTracker {
+    period: usize,
+    moves: std::collections::VecDeque<rust_decimal::Decimal>,
+    last_mid: Option<rust_decimal::Decimal>,
+}
+
+impl AtrTracker {
+    pub(crate) fn new(period: usize) -> AtrTracker {
+        AtrTracker {
+            period,
+            moves: std::collections::VecDeque::with_capacity(period),
+            last_mid: None,
+        }
+    }
+
+    /// Feed in the latest mid-price, returning the current ATR once enough
+    /// moves have been observed to fill the rolling window
+    pub(crate) fn update(&mut self, mid_price: rust_decimal::Decimal) -> Option<rust_decimal::Decimal> {
+        if let Some(last_mid) = self.last_mid {
+            if self.moves.len() == self.period {
+                self.moves.pop_front();
+            }
+            self.moves.push_back((mid_price - last_mid).abs());
+        }
+        self.last_mid = Some(mid_price);
+
+        if self.moves.len() < self.period {
+            return None;
+        }
+        let sum: rust_decimal::Decimal = self.moves.iter().sum();
+        Some(sum / rust_decimal::Decimal::from(self.moves.len() as i64))
+    }
+}
+
+/// Calculate dynamic TP/SL distances from the rolling ATR, for use with
+/// `StopMode::Trailing`.
+///
+/// The stop-loss distance (and `trail_value`) is `atr_multiplier * atr`, and
+/// the take-profit distance is `rr_ratio` times that, keeping a configurable
+/// reward:risk. Returns `(tp_price, sl_price, sl_distance)`, where
+/// `sl_distance` doubles as the trigger order's `trail_value`.
+pub(crate) fn calculate_dynamic_tp_and_sl(
+    price: rust_decimal::Decimal,
+    side: ftx::rest::Side,
+    atr: rust_decimal::Decimal,
+    atr_multiplier: rust_decimal::Decimal,
+    rr_ratio: rust_decimal::Decimal,
+    price_precision: u32) -> (rust_decimal::Decimal, rust_decimal::Decimal, rust_decimal::Decimal) {
+    let sl_distance = atr * atr_multiplier;
+    let tp_distance = sl_distance * rr_ratio;
+
+    let (tp_price, sl_price) = match side {
+        ftx::rest::Side::Buy => (price + tp_distance, price - sl_distance),
+        ftx::rest::Side::Sell => (price - tp_distance, price + sl_distance),
+    };
+    (tp_price.round_dp(price_precision), sl_price.round_dp(price_precision), sl_distance.round_dp(price_precision))
+}
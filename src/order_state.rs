@@ -0,0 +1,97 @@
+//! Explicit order-state machine, replacing the implicit position tracking
+//! inferred from `order_size`/`tp_percent`/`sl_percent` alone. Persisting the
+//! current `Order` to disk lets a restart resume correctly instead of
+//! double-entering a position or forgetting an armed stop.
+
+/// Where an order sits in its lifecycle
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum OrderState {
+    /// Entry submitted, not yet confirmed open
+    Pending,
+    /// Entry filled, triggers not yet placed
+    Open,
+    /// Take-profit trigger is live against the position, stop-loss not yet
+    TakeProfitArmed,
+    /// Both take-profit and stop-loss triggers are live against the position
+    StopLossArmed,
+    /// Position closed (the strategy loop sets this when it closes the
+    /// position at market on a side switch)
+    Filled,
+    /// Position/triggers were cancelled before completing
+    Cancelled,
+}
+
+/// A tracked order: its entry, computed TP/SL, and where it is in its
+/// lifecycle
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub(crate) struct Order {
+    pub(crate) side: crate::helpers::Side,
+    pub(crate) entry_price: rust_decimal::Decimal,
+    pub(crate) size: rust_decimal::Decimal,
+    pub(crate) tp_price: rust_decimal::Decimal,
+    pub(crate) sl_price: rust_decimal::Decimal,
+    pub(crate) state: OrderState,
+}
+
+impl Order {
+    pub(crate) fn new(
+        side: crate::helpers::Side,
+        entry_price: rust_decimal::Decimal,
+        size: rust_decimal::Decimal,
+        tp_price: rust_decimal::Decimal,
+        sl_price: rust_decimal::Decimal,
+    ) -> Order {
+        Order {
+            side,
+            entry_price,
+            size,
+            tp_price,
+            sl_price,
+            state: OrderState::Pending,
+        }
+    }
+
+    /// Advance to `to`, following the only legal transitions. Returns
+    /// `false` (leaving the state unchanged) on an illegal transition.
+    pub(crate) fn transition(&mut self, to: OrderState) -> bool {
+        let legal = matches!(
+            (self.state, to),
+            (OrderState::Pending, OrderState::Open)
+                | (OrderState::Open, OrderState::TakeProfitArmed)
+                | (OrderState::TakeProfitArmed, OrderState::StopLossArmed)
+                | (OrderState::StopLossArmed, OrderState::Filled)
+                | (OrderState::Pending, OrderState::Cancelled)
+                | (OrderState::Open, OrderState::Cancelled)
+                | (OrderState::TakeProfitArmed, OrderState::Cancelled)
+                | (OrderState::StopLossArmed, OrderState::Cancelled)
+        );
+        if legal {
+            self.state = to;
+        }
+        legal
+    }
+}
+
+/// Path `save_order_state`/`load_order_state` persist to for a given market,
+/// e.g. "order_state_BTC-PERP.json" when running several markets
+/// concurrently, so one market's resume state never clobbers another's
+pub(crate) fn order_state_filepath(market_name: &str) -> String {
+    format!("order_state_{}.json", market_name)
+}
+
+/// Persist the current order (or its absence, once filled/cancelled) to
+/// disk so a restart can resume without double-entering a position or
+/// forgetting an armed stop
+pub(crate) fn save_order_state(filepath: &str, order: Option<&Order>) -> Result<(), Box<dyn std::error::Error>> {
+    let file = std::fs::File::create(filepath)?;
+    serde_json::to_writer_pretty(file, &order)?;
+    Ok(())
+}
+
+/// Reload the order persisted by `save_order_state`, if any
+pub(crate) fn load_order_state(filepath: &str) -> Option<Order> {
+    let file = std::fs::File::open(filepath).ok()?;
+    let reader = std::io::BufReader::new(file);
+    serde_json::from_reader(reader).ok()
+}
@@ -1,6 +1,6 @@
 #[cfg(test)]
 mod test_helpers {
-    use crate::helpers::{convert_increment_to_precision, read_settings, SettingsFile, write_to_csv};
+    use crate::helpers::{convert_increment_to_precision, read_settings, Amount, CsvLogger, SettingsFile};
 
     #[test]
     fn test_convert_increment_to_precision() {
@@ -13,13 +13,13 @@ mod test_helpers {
     fn test_write_to_csv() {
         // Create a test file
         let filename = "test_write_to_csv.csv";
-        write_to_csv(
-            filename,
-            rust_decimal::Decimal::from(10 as i64),
-            rust_decimal::Decimal::from(10 as i64),
+        let mut logger = CsvLogger::new(filename, 1 as usize).unwrap();
+        logger.write_position(
+            &Amount::new(rust_decimal::Decimal::from(10 as i64), "USD".to_string()),
+            &Amount::new(rust_decimal::Decimal::from(10 as i64), "BTC".to_string()),
             &crate::helpers::Side::Sell,
-            1 as usize,
         ).unwrap();
+        logger.flush().unwrap();
 
         // Verify the file, and delete it
         let mut rdr = csv::Reader::from_path(filename).unwrap();
@@ -27,37 +27,218 @@ mod test_helpers {
             let record = result.unwrap();
             // Only compare two records
             assert_eq!(record[1], "10".to_string());
-            assert_eq!(record[2], "10".to_string());
+            assert_eq!(record[3], "10".to_string());
         };
 
+        drop(logger);
         std::fs::remove_file(filename).unwrap();
     }
 
+    #[test]
+    fn test_amount_round_to_precision() {
+        let size = Amount::from_f64(1.23456, "BTC".to_string());
+        let increment = rust_decimal::prelude::FromPrimitive::from_f64(0.001).unwrap();
+        let precision = convert_increment_to_precision(increment);
+        let rounded = size.round_to_precision(precision);
+        assert_eq!(rounded.quantity.to_string(), "1.235");
+        assert_eq!(rounded.currency, "BTC");
+    }
+
     #[test]
     fn test_read_settings() {
         // Create a test file
         let filename = "test_read_settings.json";
         let data = SettingsFile {
             market_name: "BTC-USD".to_string(),
-            time_delta: 1,
+            sampling_time: 1,
             bb_period: 10,
-            bb_std_dev: 0.0,
+            bb_std_dev: rust_decimal::Decimal::ZERO,
             orderbook_depth: 0,
             live: false,
             order_size: Default::default(),
             tp_percent: Default::default(),
             sl_percent: Default::default(),
             write_to_file: false,
+            order_kind: Default::default(),
+            backtest_file: Default::default(),
+            feed: Default::default(),
+            candle_interval: Default::default(),
+            bb_on_candle_close: Default::default(),
+            stop_mode: Default::default(),
+            atr_period: Default::default(),
+            atr_multiplier: Default::default(),
+            rr_ratio: Default::default(),
+            csv_flush_interval: Default::default(),
+            markets: Default::default(),
+            max_position: Default::default(),
+            min_spread: Default::default(),
+            resume_only: Default::default(),
         };
         serde_json::to_writer_pretty(
             &std::fs::File::create(filename).unwrap(), &data).unwrap();
 
         // Verify the test file, and delete it
         let settings = read_settings(filename);
-        assert_eq!(settings.time_delta, 1 as u64);
+        assert_eq!(settings.sampling_time, 1 as u64);
         assert_eq!(settings.bb_period, 10 as usize);
-        assert_eq!(settings.bb_std_dev, 0 as f64);
+        assert_eq!(settings.bb_std_dev, rust_decimal::Decimal::ZERO);
         assert_eq!(settings.orderbook_depth, 0 as u32);
         std::fs::remove_file(filename).unwrap();
     }
+
+    #[test]
+    fn test_read_settings_risk_limits_default() {
+        let filename = "test_read_settings_risk_limits_default.json";
+        std::fs::write(filename, r#"{
+            "market_name": "BTC-PERP",
+            "sampling_time": 1,
+            "bb_period": 10,
+            "bb_std_dev": "2.0",
+            "orderbook_depth": 5,
+            "live": false,
+            "order_size": "1.0",
+            "tp_percent": "0.01",
+            "sl_percent": "0.01",
+            "write_to_file": false
+        }"#).unwrap();
+
+        let settings = read_settings(filename);
+        assert_eq!(settings.max_position, rust_decimal::Decimal::MAX);
+        assert_eq!(settings.min_spread, rust_decimal::Decimal::ZERO);
+        assert_eq!(settings.resume_only, false);
+
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn test_read_settings_legacy_single_market() {
+        let filename = "test_read_settings_legacy.json";
+        std::fs::write(filename, r#"{
+            "market_name": "BTC-PERP",
+            "sampling_time": 1,
+            "bb_period": 10,
+            "bb_std_dev": "2.0",
+            "orderbook_depth": 5,
+            "live": false,
+            "order_size": "1.0",
+            "tp_percent": "0.01",
+            "sl_percent": "0.01",
+            "write_to_file": false
+        }"#).unwrap();
+
+        let settings = read_settings(filename);
+        let markets = settings.market_configs();
+        assert_eq!(markets.len(), 1);
+        assert_eq!(markets[0].market_name, "BTC-PERP");
+        assert_eq!(markets[0].bb_period, 10);
+
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn test_read_settings_multi_market() {
+        let filename = "test_read_settings_multi.json";
+        std::fs::write(filename, r#"{
+            "market_name": "BTC-PERP",
+            "sampling_time": 1,
+            "bb_period": 10,
+            "bb_std_dev": "2.0",
+            "orderbook_depth": 5,
+            "live": false,
+            "order_size": "1.0",
+            "tp_percent": "0.01",
+            "sl_percent": "0.01",
+            "write_to_file": false,
+            "markets": [
+                {
+                    "market_name": "BTC-PERP",
+                    "bb_period": 10,
+                    "bb_std_dev": "2.0",
+                    "orderbook_depth": 5,
+                    "order_size": "1.0",
+                    "tp_percent": "0.01",
+                    "sl_percent": "0.01"
+                },
+                {
+                    "market_name": "ETH-PERP",
+                    "bb_period": 20,
+                    "bb_std_dev": "1.5",
+                    "orderbook_depth": 10,
+                    "order_size": "2.0",
+                    "tp_percent": "0.02",
+                    "sl_percent": "0.02"
+                }
+            ]
+        }"#).unwrap();
+
+        let settings = read_settings(filename);
+        let markets = settings.market_configs();
+        assert_eq!(markets.len(), 2);
+        assert_eq!(markets[0].market_name, "BTC-PERP");
+        assert_eq!(markets[1].market_name, "ETH-PERP");
+        assert_eq!(markets[1].bb_period, 20);
+
+        std::fs::remove_file(filename).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod test_order_state {
+    use crate::helpers::Side;
+    use crate::order_state::{load_order_state, save_order_state, Order, OrderState};
+
+    #[test]
+    fn test_legal_transitions() {
+        let mut order = Order::new(
+            Side::Buy,
+            rust_decimal::Decimal::from(100 as i64),
+            rust_decimal::Decimal::from(1 as i64),
+            rust_decimal::Decimal::from(110 as i64),
+            rust_decimal::Decimal::from(95 as i64),
+        );
+        assert_eq!(order.state, OrderState::Pending);
+        assert!(order.transition(OrderState::Open));
+        assert!(order.transition(OrderState::TakeProfitArmed));
+        assert!(order.transition(OrderState::StopLossArmed));
+        assert!(order.transition(OrderState::Filled));
+        assert_eq!(order.state, OrderState::Filled);
+    }
+
+    #[test]
+    fn test_illegal_transition_is_rejected() {
+        let mut order = Order::new(
+            Side::Sell,
+            rust_decimal::Decimal::from(100 as i64),
+            rust_decimal::Decimal::from(1 as i64),
+            rust_decimal::Decimal::from(90 as i64),
+            rust_decimal::Decimal::from(105 as i64),
+        );
+        assert!(!order.transition(OrderState::Filled));
+        assert_eq!(order.state, OrderState::Pending);
+    }
+
+    #[test]
+    fn test_save_and_load_order_state() {
+        let original_dir = std::env::current_dir().unwrap();
+        let tmp_dir = std::env::temp_dir();
+        std::env::set_current_dir(&tmp_dir).unwrap();
+
+        let mut order = Order::new(
+            Side::Buy,
+            rust_decimal::Decimal::from(100 as i64),
+            rust_decimal::Decimal::from(1 as i64),
+            rust_decimal::Decimal::from(110 as i64),
+            rust_decimal::Decimal::from(95 as i64),
+        );
+        order.transition(OrderState::Open);
+
+        let filepath = "test_order_state.json";
+        save_order_state(filepath, Some(&order)).unwrap();
+        let reloaded = load_order_state(filepath).unwrap();
+        assert_eq!(reloaded.side, order.side);
+        assert_eq!(reloaded.state, order.state);
+
+        std::fs::remove_file(filepath).unwrap();
+        std::env::set_current_dir(&original_dir).unwrap();
+    }
 }
\ No newline at end of file